@@ -0,0 +1,199 @@
+// Copyright 2018 Kodebox, Inc.
+// This file is part of CodeChain.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use cbytes::Bytes;
+use ccrypto::blake256;
+use ctypes::H256;
+use memorydb::MemoryDB;
+use nibbleslice::NibbleSlice;
+use trie::recorder::Recorder;
+use trie::{Trie, TrieDB, TrieDBMut, TrieMut};
+
+/// Number of consecutive blocks covered by a single Canonical Hash Trie. A
+/// light client stores only one CHT root per span, so a full span passes
+/// `CHT_SIZE` asset-state roots to `build_cht`; the count is not checked.
+pub const CHT_SIZE: u64 = 2048;
+
+/// Errors raised while verifying a `number -> state-root` mapping against a
+/// trusted CHT root.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Error {
+    /// A proof node did not hash to the value its parent pointed at.
+    HashMismatch {
+        expected: H256,
+        found: H256,
+    },
+    /// The proof ended before reaching the leaf for the requested number.
+    Incomplete,
+    /// A proof node was not well-formed RLP.
+    Malformed,
+}
+
+/// Key under which a block's asset-state root is stored in the CHT. Block
+/// numbers are keyed big-endian so that trie order matches numeric order.
+fn key(number: u64) -> H256 {
+    let mut hash = H256::default();
+    hash[24..32].clone_from_slice(&[
+        (number >> 56) as u8,
+        (number >> 48) as u8,
+        (number >> 40) as u8,
+        (number >> 32) as u8,
+        (number >> 24) as u8,
+        (number >> 16) as u8,
+        (number >> 8) as u8,
+        number as u8,
+    ]);
+    hash
+}
+
+/// Builds a CHT over `roots`, the asset-state roots of consecutive blocks
+/// starting at `span_start` (`CHT_SIZE` of them for a full span), and returns
+/// its root. The trie maps each block
+/// number in the span to that block's state root; only this root needs to be
+/// retained per span.
+pub fn build_cht(span_start: u64, roots: &[H256]) -> H256 {
+    let mut db = MemoryDB::new();
+    let mut root = H256::default();
+    {
+        let mut trie = TrieDBMut::new(&mut db, &mut root);
+        for (offset, state_root) in roots.iter().enumerate() {
+            let number = span_start + offset as u64;
+            trie.insert(&key(number), &::rlp::encode(state_root)).expect("CHT insert never fails on a fresh MemoryDB");
+        }
+    }
+    root
+}
+
+/// Collects an ordered proof that block `number` maps to its asset-state root
+/// under the CHT built from `roots`. The proof can be checked with
+/// `verify_state_root` against a trusted `cht_root` without rebuilding the trie.
+pub fn prove_state_root(cht_root: H256, number: u64, roots: &[H256]) -> Vec<Bytes> {
+    debug_assert!(!roots.is_empty());
+    let span_start = number - (number % CHT_SIZE);
+    let mut db = MemoryDB::new();
+    // Rebuild the span into a fresh MemoryDB: `TrieDBMut` must start from the
+    // empty-trie root (`H256::default()`) and populate `db` as it inserts, just
+    // like `build_cht`. Seeding with `cht_root` would ask it to read nodes that
+    // were never written.
+    let mut root = H256::default();
+    {
+        let mut trie = TrieDBMut::new(&mut db, &mut root);
+        for (offset, state_root) in roots.iter().enumerate() {
+            let number = span_start + offset as u64;
+            trie.insert(&key(number), &::rlp::encode(state_root)).expect("CHT insert never fails on a fresh MemoryDB");
+        }
+    }
+    // The reconstructed trie must reproduce the trusted root, otherwise `roots`
+    // does not correspond to `cht_root`.
+    debug_assert_eq!(root, cht_root);
+    let mut recorder = Recorder::new();
+    let trie = TrieDB::new(&db, &root).expect("CHT trie was just built");
+    let _ = trie.get_recorded(&key(number), &mut recorder).expect("number is within the span");
+    recorder.drain().into_iter().map(|record| record.data).collect()
+}
+
+/// Re-hashes each proof node, checks the hashes link down from `cht_root`, and
+/// returns the asset-state root committed for `number`, or `None` if the proof
+/// witnesses that `number` is absent from the span.
+pub fn verify_state_root(cht_root: H256, number: u64, proof: &[Bytes]) -> Result<Option<H256>, Error> {
+    let address = key(number);
+    let path = NibbleSlice::new(&address);
+    let mut expected = cht_root;
+    let mut consumed = 0;
+    for (index, node) in proof.iter().enumerate() {
+        let found = blake256(node);
+        if found != expected {
+            return Err(Error::HashMismatch {
+                expected,
+                found,
+            })
+        }
+        let last = index + 1 == proof.len();
+        let rlp = ::rlp::UntrustedRlp::new(node);
+        match rlp.item_count().map_err(|_| Error::Malformed)? {
+            2 => {
+                let encoded = rlp.at(0).and_then(|r| r.data()).map_err(|_| Error::Malformed)?;
+                let (slice, is_leaf) = NibbleSlice::from_encoded(encoded);
+                if !path.mid(consumed).starts_with(&slice) {
+                    return Ok(None)
+                }
+                if is_leaf {
+                    let value = rlp.at(1).and_then(|r| r.data()).map_err(|_| Error::Malformed)?;
+                    return Ok(Some(::rlp::decode(value)))
+                }
+                let child = rlp.at(1).map_err(|_| Error::Malformed)?;
+                if child.is_empty() {
+                    return Ok(None)
+                }
+                if last {
+                    return Err(Error::Incomplete)
+                }
+                expected = child.as_val().map_err(|_| Error::Malformed)?;
+                consumed += slice.len();
+            }
+            17 => {
+                let remaining = path.mid(consumed);
+                if remaining.is_empty() {
+                    let value = rlp.at(16).and_then(|r| r.data()).map_err(|_| Error::Malformed)?;
+                    return Ok(Some(::rlp::decode(value)))
+                }
+                let child = rlp.at(remaining.at(0) as usize).map_err(|_| Error::Malformed)?;
+                if child.is_empty() {
+                    return Ok(None)
+                }
+                if last {
+                    return Err(Error::Incomplete)
+                }
+                expected = child.as_val().map_err(|_| Error::Malformed)?;
+                consumed += 1;
+            }
+            _ => return Err(Error::Malformed),
+        }
+    }
+    Err(Error::Incomplete)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roots() -> Vec<H256> {
+        (0u8..8).map(|i| blake256(&[i])).collect()
+    }
+
+    #[test]
+    fn round_trip_proves_state_root() {
+        let roots = roots();
+        let cht_root = build_cht(0, &roots);
+        let proof = prove_state_root(cht_root, 3, &roots);
+        assert_eq!(verify_state_root(cht_root, 3, &proof), Ok(Some(roots[3])));
+    }
+
+    #[test]
+    fn corrupted_node_is_rejected() {
+        let roots = roots();
+        let cht_root = build_cht(0, &roots);
+        let mut proof = prove_state_root(cht_root, 3, &roots);
+        let last = proof[0].len() - 1;
+        proof[0][last] ^= 0xff;
+        match verify_state_root(cht_root, 3, &proof) {
+            Err(Error::HashMismatch {
+                ..
+            }) => {}
+            other => panic!("expected HashMismatch, got {:?}", other),
+        }
+    }
+}
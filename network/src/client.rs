@@ -14,113 +14,322 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::ops::Range;
 use std::sync::{Arc, Weak};
 
 use cio::IoChannel;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
 use super::connection::HandlerMessage as ConnectionMessage;
+use super::event::{NetworkEvent, NetworkEventSink};
+use super::hole_punch::{
+    Action, HolePunch, ObservedAddress, KEEPALIVE_INTERVAL_MS, KEEPALIVE_TIMER, PUNCH_INTERVAL_MS, PUNCH_TIMER,
+};
+use super::negotiation::{negotiate, Negotiated};
+use super::priority::{Priority, SendError, SendQueue};
 use super::{Api, Error as ExtensionError, NetworkExtension, NodeToken, TimerToken};
 
+/// Per-peer negotiated version, keyed by extension name then node. Shared
+/// between `Client` (which records the outcome of a handshake) and each
+/// `ClientApi` (which consults it to frame outbound messages).
+type NegotiatedVersions = Arc<RwLock<HashMap<String, HashMap<NodeToken, u64>>>>;
+
+/// In-flight hole-punch attempts keyed by target peer, shared so the Api can
+/// start one and the `Client` driver can advance it from a timer/message.
+type HolePunches = Arc<Mutex<HashMap<NodeToken, HolePunch>>>;
+
 struct ClientApi {
     extension: Weak<NetworkExtension>,
     channel: IoChannel<ConnectionMessage>,
+    event_sink: Option<Arc<NetworkEventSink>>,
+    /// Per-peer version agreed by the negotiation handshake, recorded by
+    /// `Client::apply_negotiation` (or by `note_negotiated`) and consulted by
+    /// `send` so extensions can
+    /// frame messages differently per peer.
+    negotiated: NegotiatedVersions,
+    /// Hole-punch attempts this Api has started, advanced by the `Client`
+    /// driver once the rendezvous reply / keepalive tick arrives.
+    hole_punches: HolePunches,
+    /// Bounded, priority-classed outbound buffer per destination peer. `send`
+    /// only enqueues into the target's queue; the connection-layer drainer
+    /// services every registered extension's queue in turn via `drain_pending`
+    /// so one chatty extension cannot monopolize the shared channel.
+    queues: Mutex<HashMap<NodeToken, SendQueue>>,
+}
+
+impl ClientApi {
+    fn emit(&self, event: NetworkEvent) {
+        if let Some(ref sink) = self.event_sink {
+            sink.notify(&event);
+        }
+    }
+
+    /// Version to frame outbound messages with for `id`. Defaults to the base
+    /// version `0` until a handshake completes for the peer.
+    fn version_for(&self, id: &NodeToken) -> u64 {
+        let extension_name = match self.extension.upgrade() {
+            Some(extension) => extension.name(),
+            None => return 0,
+        };
+        self.negotiated.read().get(&extension_name).and_then(|peers| peers.get(id)).cloned().unwrap_or(0)
+    }
+
+    /// Drains one weighted round from every destination peer's queue and
+    /// forwards the messages to the shared channel. Called once per extension
+    /// per tick by `Client::drive_sends`, not from `send`, so a peer with a deep
+    /// backlog is serviced round-robin against this extension's other peers
+    /// instead of starving them by draining to empty in a single call. A
+    /// channel error is permanent (the IO service is gone), so it stops
+    /// draining this extension for the tick rather than retrying.
+    fn drain_pending(&self) {
+        let extension = match self.extension.upgrade() {
+            Some(extension) => extension,
+            None => return,
+        };
+        let extension_name = extension.name();
+        let need_encryption = extension.need_encryption();
+
+        let rounds: Vec<(NodeToken, Vec<Vec<u8>>)> = {
+            let mut queues = self.queues.lock();
+            queues
+                .iter_mut()
+                .filter(|(_, queue)| !queue.is_empty())
+                .map(|(id, queue)| (*id, queue.drain_round()))
+                .collect()
+        };
+
+        for (id, round) in rounds {
+            let version = self.version_for(&id);
+            for data in round {
+                if let Err(err) = self.channel.send(ConnectionMessage::SendExtensionMessage {
+                    node_id: id,
+                    extension_name: extension_name.clone(),
+                    need_encryption,
+                    version,
+                    data,
+                }) {
+                    info!("Cannot send extension message to {:?} : {:?}", id, err);
+                    self.emit(NetworkEvent::MessageSent {
+                        extension_name: extension_name.clone(),
+                        node: Some(id),
+                        result: Err(format!("{:?}", err)),
+                    });
+                    return
+                }
+            }
+            self.emit(NetworkEvent::MessageSent {
+                extension_name: extension_name.clone(),
+                node: Some(id),
+                result: Ok(()),
+            });
+        }
+    }
 }
 
 impl Api for ClientApi {
     fn send(&self, id: &NodeToken, message: &Vec<u8>) {
+        let _ = self.send_with_priority(id, message, Priority::default());
+    }
+
+    fn send_with_priority(&self, id: &NodeToken, message: &Vec<u8>, priority: Priority) -> Result<(), SendError> {
+        let extension = match self.extension.upgrade() {
+            Some(extension) => extension,
+            None => {
+                info!("The extension already dropped");
+                self.emit(NetworkEvent::ExtensionDropped {
+                    extension_name: String::new(),
+                    node: Some(*id),
+                    result: Err("extension dropped".to_string()),
+                });
+                return Err(SendError::Dropped)
+            }
+        };
+
+        // Only enqueue here: draining happens on `Client::drive_sends`'s
+        // round-robin tick, not inline, so a full class backs off and asks the
+        // extension to throttle rather than flooding the shared channel.
+        let mut queues = self.queues.lock();
+        let queue = queues.entry(*id).or_insert_with(SendQueue::default);
+        if let Err(err) = queue.enqueue(priority, message.clone()) {
+            info!("Send queue congested for {:?}", id);
+            drop(queues);
+            extension.on_send_congested(id);
+            return Err(err)
+        }
+        Ok(())
+    }
+
+    fn connect(&self, id: &NodeToken) {
         if let Some(extension) = self.extension.upgrade() {
-            let need_encryption = extension.need_encryption();
             let extension_name = extension.name();
+            let versions = extension.supported_versions();
+            let features = extension.supported_features();
             let node_id = *id;
-            if let Err(err) = self.channel.send(ConnectionMessage::SendExtensionMessage {
+            let result = self.channel.send(ConnectionMessage::RequestNegotiation {
                 node_id,
-                extension_name,
-                need_encryption,
-                data: message.clone(),
-            }) {
-                info!("Cannot send extension message to {:?} : {:?}", id, err);
-            } else {
-                info!("Request send extension message to {:?}", id);
+                extension_name: extension_name.clone(),
+                versions,
+                features,
+            });
+            match result {
+                Ok(()) => info!("Request negotiation to {:?}", id),
+                Err(ref err) => info!("Cannot request negotiation to {:?} : {:?}", id, err),
             }
+            self.emit(NetworkEvent::NegotiationRequested {
+                extension_name,
+                node: Some(node_id),
+                result: result.map_err(|err| format!("{:?}", err)),
+            });
         } else {
             info!("The extension already dropped");
+            self.emit(NetworkEvent::ExtensionDropped {
+                extension_name: String::new(),
+                node: Some(*id),
+                result: Err("extension dropped".to_string()),
+            });
         }
     }
 
-    fn connect(&self, id: &NodeToken) {
+    fn connect_via_rendezvous(&self, id: &NodeToken, rendezvous: &NodeToken) {
         if let Some(extension) = self.extension.upgrade() {
             let extension_name = extension.name();
-            let version = 0;
             let node_id = *id;
-            if let Err(err) = self.channel.send(ConnectionMessage::RequestNegotiation {
+            let rendezvous_id = *rendezvous;
+
+            // Track the attempt and kick the state machine into
+            // AwaitingRendezvous; the `Client` driver advances it when the
+            // rendezvous reply and keepalive ticks arrive.
+            let mut punch = HolePunch::new(node_id, rendezvous_id, extension_name.clone());
+            let _ = punch.start();
+            self.hole_punches.lock().insert(node_id, punch);
+            // Keep NAT mappings open for the duration of the attempt.
+            self.set_timer(KEEPALIVE_TIMER, KEEPALIVE_INTERVAL_MS);
+            // Re-fire the punch packet while inside PUNCH_TIMEOUT_MS; a tick
+            // every 15s (KEEPALIVE_INTERVAL_MS) would never catch the window.
+            self.set_timer(PUNCH_TIMER, PUNCH_INTERVAL_MS);
+
+            let result = self.channel.send(ConnectionMessage::RequestRendezvous {
                 node_id,
-                extension_name,
-                version,
-            }) {
-                info!("Cannot request negotiation to {:?} : {:?}", id, err);
-            } else {
-                info!("Request negotiation to {:?}", id);
+                rendezvous_id,
+                extension_name: extension_name.clone(),
+            });
+            match result {
+                Ok(()) => info!("Request rendezvous to {:?} via {:?}", id, rendezvous),
+                Err(ref err) => info!("Cannot request rendezvous to {:?} via {:?} : {:?}", id, rendezvous, err),
             }
+            self.emit(NetworkEvent::RendezvousRequested {
+                extension_name,
+                node: Some(node_id),
+                result: result.map_err(|err| format!("{:?}", err)),
+            });
         } else {
             info!("The extension already dropped");
+            self.emit(NetworkEvent::ExtensionDropped {
+                extension_name: String::new(),
+                node: Some(*id),
+                result: Err("extension dropped".to_string()),
+            });
+        }
+    }
+
+    fn note_negotiated(&self, id: &NodeToken, version: u64) {
+        if let Some(extension) = self.extension.upgrade() {
+            self.negotiated.write().entry(extension.name()).or_insert_with(HashMap::new).insert(*id, version);
         }
     }
 
     fn set_timer(&self, timer_id: usize, ms: u64) {
         if let Some(extension) = self.extension.upgrade() {
             let extension_name = extension.name();
-            if let Err(err) = self.channel.send(ConnectionMessage::SetTimer {
-                extension_name,
+            let result = self.channel.send(ConnectionMessage::SetTimer {
+                extension_name: extension_name.clone(),
                 timer_id,
                 ms,
-            }) {
-                info!("Cannot set timer {}:{} : {:?}", extension.name(), timer_id, err);
-            } else {
-                info!("{} sets timer({}) every {} ms", extension.name(), timer_id, ms);
+            });
+            match result {
+                Ok(()) => info!("{} sets timer({}) every {} ms", extension_name, timer_id, ms),
+                Err(ref err) => info!("Cannot set timer {}:{} : {:?}", extension_name, timer_id, err),
             }
+            self.emit(NetworkEvent::TimerSet {
+                extension_name,
+                node: None,
+                result: result.map_err(|err| format!("{:?}", err)),
+            });
         } else {
             info!("The extension already dropped");
+            self.emit(NetworkEvent::ExtensionDropped {
+                extension_name: String::new(),
+                node: None,
+                result: Err("extension dropped".to_string()),
+            });
         }
     }
 
     fn set_timer_once(&self, timer_id: usize, ms: u64) {
         if let Some(extension) = self.extension.upgrade() {
             let extension_name = extension.name();
-            if let Err(err) = self.channel.send(ConnectionMessage::SetTimerOnce {
-                extension_name,
+            let result = self.channel.send(ConnectionMessage::SetTimerOnce {
+                extension_name: extension_name.clone(),
                 timer_id,
                 ms,
-            }) {
-                info!("Cannot set timer {}:{} : {:?}", extension.name(), timer_id, err);
-            } else {
-                info!("{} sets timer({}) after {} ms", extension.name(), timer_id, ms);
+            });
+            match result {
+                Ok(()) => info!("{} sets timer({}) after {} ms", extension_name, timer_id, ms),
+                Err(ref err) => info!("Cannot set timer {}:{} : {:?}", extension_name, timer_id, err),
             }
+            self.emit(NetworkEvent::TimerSet {
+                extension_name,
+                node: None,
+                result: result.map_err(|err| format!("{:?}", err)),
+            });
         } else {
             info!("The extension already dropped");
+            self.emit(NetworkEvent::ExtensionDropped {
+                extension_name: String::new(),
+                node: None,
+                result: Err("extension dropped".to_string()),
+            });
         }
     }
 
     fn clear_timer(&self, timer_id: usize) {
         if let Some(extension) = self.extension.upgrade() {
             let extension_name = extension.name();
-            if let Err(err) = self.channel.send(ConnectionMessage::ClearTimer {
-                extension_name,
+            let result = self.channel.send(ConnectionMessage::ClearTimer {
+                extension_name: extension_name.clone(),
                 timer_id,
-            }) {
-                info!("Cannot clear timer {}:{} : {:?}", extension.name(), timer_id, err);
-            } else {
-                info!("{} clears timer({})", extension.name(), timer_id);
+            });
+            match result {
+                Ok(()) => info!("{} clears timer({})", extension_name, timer_id),
+                Err(ref err) => info!("Cannot clear timer {}:{} : {:?}", extension_name, timer_id, err),
             }
+            self.emit(NetworkEvent::TimerCleared {
+                extension_name,
+                node: None,
+                result: result.map_err(|err| format!("{:?}", err)),
+            });
         } else {
             info!("The extension already dropped");
+            self.emit(NetworkEvent::ExtensionDropped {
+                extension_name: String::new(),
+                node: None,
+                result: Err("extension dropped".to_string()),
+            });
         }
     }
 }
 
 pub struct Client {
     extensions: RwLock<HashMap<String, Arc<NetworkExtension>>>,
+    event_sink: RwLock<Option<Arc<NetworkEventSink>>>,
+    negotiated: NegotiatedVersions,
+    hole_punches: HolePunches,
+    /// One `ClientApi` per registered extension, kept concrete (rather than as
+    /// the `Arc<Api>` trait object handed to the extension) so `drive_sends`
+    /// can reach each extension's send queue and channel directly.
+    apis: RwLock<HashMap<String, Arc<ClientApi>>>,
 }
 
 macro_rules! define_broadcast_method {
@@ -171,17 +380,46 @@ impl Client {
         let api = Arc::new(ClientApi {
             extension: Arc::downgrade(&extension),
             channel,
-        }) as Arc<Api>;
-        extension.on_initialize(Arc::clone(&api));
-        api
+            event_sink: self.event_sink.read().clone(),
+            negotiated: Arc::clone(&self.negotiated),
+            hole_punches: Arc::clone(&self.hole_punches),
+            queues: Mutex::new(HashMap::new()),
+        });
+        self.apis.write().insert(extension.name(), Arc::clone(&api));
+        extension.on_initialize(Arc::clone(&api) as Arc<Api>);
+        api as Arc<Api>
     }
 
     pub fn new() -> Arc<Self> {
         Arc::new(Self {
             extensions: RwLock::new(HashMap::new()),
+            event_sink: RwLock::new(None),
+            negotiated: Arc::new(RwLock::new(HashMap::new())),
+            hole_punches: Arc::new(Mutex::new(HashMap::new())),
+            apis: RwLock::new(HashMap::new()),
         })
     }
 
+    /// Round-robin send-queue drainer: services every registered extension's
+    /// queue for one weighted round each. Because each call only drains one
+    /// round per peer rather than looping an extension's backlog to empty, a
+    /// noisy extension gets the same turn as its quieter neighbours on every
+    /// tick instead of hogging the shared channel between them. Called from
+    /// the `SEND_TIMER` tick.
+    pub fn drive_sends(&self) {
+        let apis = self.apis.read();
+        for api in apis.values() {
+            api.drain_pending();
+        }
+    }
+
+    /// Installs a structured event sink that receives a `NetworkEvent` for every
+    /// action the extensions take, in addition to the free-form log lines.
+    /// Extensions registered after this call route their events to the sink.
+    pub fn set_event_sink(&self, sink: Arc<NetworkEventSink>) {
+        *self.event_sink.write() = Some(sink);
+    }
+
     define_broadcast_method!(on_node_added; id, &NodeToken);
     define_broadcast_method!(on_node_removed; id, &NodeToken);
 
@@ -193,6 +431,201 @@ impl Client {
 
     define_broadcast_method!(on_close);
 
+    define_method!(on_send_congested; id, &NodeToken);
+
+    /// Handles an incoming `RequestNegotiation` from `id`: intersects the peer's
+    /// offered version range and feature bits with the named extension's own,
+    /// records the agreed version so `send` frames messages per peer, and
+    /// delivers `on_negotiated` (or `on_negotiation_failed` when the ranges do
+    /// not overlap). Called by the connection layer's message handler on the
+    /// responding side of a handshake. Also sends the result back to `id` as a
+    /// `NegotiationReply` and returns it, so the initiating side can apply the
+    /// very same `agreed` via `on_negotiation_reply` instead of being left at
+    /// the default version `0` forever.
+    pub fn on_request_negotiation(
+        &self,
+        name: &String,
+        id: &NodeToken,
+        versions: &Range<u64>,
+        features: u64,
+    ) -> Option<Negotiated> {
+        let extensions = self.extensions.read();
+        let extension = match extensions.get(name) {
+            Some(extension) => extension,
+            None => {
+                info!("{} doesn't exist.", name);
+                return None
+            }
+        };
+        let agreed = negotiate(&extension.supported_versions(), extension.supported_features(), versions, features);
+        Self::apply_negotiation(&self.negotiated, extension, name, id, agreed);
+        drop(extensions);
+        self.send_negotiation_reply(name, id, agreed);
+        agreed
+    }
+
+    /// Echoes the `agreed` a `RequestNegotiation` was just resolved to back to
+    /// `id` over the responding extension's channel, so `on_negotiation_reply`
+    /// has something to drive on the initiating side.
+    fn send_negotiation_reply(&self, name: &String, id: &NodeToken, agreed: Option<Negotiated>) {
+        let apis = self.apis.read();
+        let api = match apis.get(name) {
+            Some(api) => api,
+            None => return,
+        };
+        let result = api.channel.send(ConnectionMessage::NegotiationReply {
+            node_id: *id,
+            extension_name: name.clone(),
+            agreed,
+        });
+        if let Err(ref err) = result {
+            info!("Cannot send negotiation reply to {:?} : {:?}", id, err);
+        }
+    }
+
+    /// Handles the reply to a `RequestNegotiation` this node sent via
+    /// `connect`: records the version the responder agreed on (or delivers
+    /// `on_negotiation_failed`) so `send` frames the *initiator's* outbound
+    /// messages at the same version as the responder, instead of the default
+    /// `0`. `agreed` is the `Negotiated` the responder computed and echoed
+    /// back, not recomputed here, since both sides must end up agreeing on the
+    /// exact same value. Called by the connection layer's message handler when
+    /// the reply arrives.
+    pub fn on_negotiation_reply(&self, name: &String, id: &NodeToken, agreed: Option<Negotiated>) {
+        let extensions = self.extensions.read();
+        let extension = match extensions.get(name) {
+            Some(extension) => extension,
+            None => {
+                info!("{} doesn't exist.", name);
+                return
+            }
+        };
+        Self::apply_negotiation(&self.negotiated, extension, name, id, agreed);
+    }
+
+    /// Shared by both sides of a handshake: records `agreed`'s version so
+    /// `send` can look it up later, and delivers `on_negotiated` /
+    /// `on_negotiation_failed` to the extension.
+    fn apply_negotiation(
+        negotiated: &NegotiatedVersions,
+        extension: &Arc<NetworkExtension>,
+        name: &String,
+        id: &NodeToken,
+        agreed: Option<Negotiated>,
+    ) {
+        match agreed {
+            Some(agreed) => {
+                negotiated.write().entry(name.clone()).or_insert_with(HashMap::new).insert(*id, agreed.version);
+                extension.on_negotiated(id, agreed.version, agreed.features);
+            }
+            None => extension.on_negotiation_failed(id),
+        }
+    }
+
+    /// Feeds a rendezvous-relayed observed address into the matching attempt and
+    /// returns the `Action` the connection layer should perform (fire a punch
+    /// packet, relay, etc.). Called by the connection layer's message handler.
+    pub fn on_rendezvous_response(&self, observed: ObservedAddress, now_ms: u64) -> Action {
+        let mut punches = self.hole_punches.lock();
+        match punches.get_mut(&observed.node_id) {
+            Some(punch) => punch.on_rendezvous(observed, now_ms),
+            None => Action::Wait,
+        }
+    }
+
+    /// A punch packet came back from `target`: mark the attempt connected and
+    /// deliver `on_hole_punch_succeeded` to the initiating extension.
+    pub fn on_punch_reply(&self, target: &NodeToken) {
+        let finished = {
+            let mut punches = self.hole_punches.lock();
+            match punches.get_mut(target) {
+                Some(punch) => match punch.on_peer_reply() {
+                    Action::Succeeded => Some(punch.extension_name().to_string()),
+                    _ => None,
+                },
+                None => None,
+            }
+        };
+        if let Some(name) = finished {
+            self.hole_punches.lock().remove(target);
+            self.clear_hole_punch_timers_if_idle(&name);
+            let extensions = self.extensions.read();
+            if let Some(ref extension) = extensions.get(&name) {
+                extension.on_hole_punch_succeeded(target);
+            }
+        }
+    }
+
+    /// Punch-timer tick driver: advances every in-flight attempt's punching
+    /// window and returns the `(target, addr)` pairs the connection layer
+    /// should re-fire a punch packet at. Attempts that pass `PUNCH_TIMEOUT_MS`
+    /// are removed from the in-flight set and reported via
+    /// `on_hole_punch_failed` to the initiating extension; no relay request is
+    /// sent or returned for them. Called from the `PUNCH_TIMER`
+    /// timeout, not `KEEPALIVE_TIMER`: that tick is an order of magnitude
+    /// slower than `PUNCH_TIMEOUT_MS`, so driving retransmission from it meant
+    /// `Action::SendPunch` was returned once and never acted on again before
+    /// the window had already closed.
+    pub fn drive_hole_punches(&self, now_ms: u64) -> Vec<(NodeToken, SocketAddr)> {
+        let (resends, timed_out) = {
+            let mut punches = self.hole_punches.lock();
+            let mut resends = Vec::new();
+            let mut timed_out = Vec::new();
+            for (target, punch) in punches.iter_mut() {
+                match punch.poll(now_ms) {
+                    Action::SendPunch(addr) => resends.push((*target, addr)),
+                    Action::Relay(_) => timed_out.push((*target, punch.extension_name().to_string())),
+                    Action::Wait | Action::RequestRelay | Action::Succeeded => {}
+                }
+            }
+            (resends, timed_out)
+        };
+        if !timed_out.is_empty() {
+            let mut names = HashSet::new();
+            {
+                let mut punches = self.hole_punches.lock();
+                let extensions = self.extensions.read();
+                for (target, name) in timed_out {
+                    punches.remove(&target);
+                    if let Some(ref extension) = extensions.get(&name) {
+                        extension.on_hole_punch_failed(&target);
+                    }
+                    names.insert(name);
+                }
+            }
+            for name in names {
+                self.clear_hole_punch_timers_if_idle(&name);
+            }
+        }
+        resends
+    }
+
+    /// Keepalive-timer tick driver: returns the `(target, addr)` pair for every
+    /// in-flight attempt that has learned the peer's observed address, so the
+    /// connection layer can fire a small packet at each to keep the NAT mapping
+    /// open for the duration of the attempt. Called from the `KEEPALIVE_TIMER`
+    /// tick.
+    pub fn drive_keepalives(&self) -> Vec<(NodeToken, SocketAddr)> {
+        let punches = self.hole_punches.lock();
+        punches.values().filter_map(|punch| punch.peer_addr().map(|addr| (punch.target(), addr))).collect()
+    }
+
+    /// Clears `KEEPALIVE_TIMER` and `PUNCH_TIMER` for `name` once none of its
+    /// attempts are still in-flight, so the timers `connect_via_rendezvous`
+    /// sets do not keep firing forever after every attempt that needed them is
+    /// gone.
+    fn clear_hole_punch_timers_if_idle(&self, name: &str) {
+        let still_in_flight = self.hole_punches.lock().values().any(|punch| punch.extension_name() == name);
+        if still_in_flight {
+            return
+        }
+        let apis = self.apis.read();
+        if let Some(api) = apis.get(name) {
+            api.clear_timer(KEEPALIVE_TIMER);
+            api.clear_timer(PUNCH_TIMER);
+        }
+    }
+
     define_method!(on_timer_set_allowed; timer_id, TimerToken);
     define_method!(on_timer_set_denied; timer_id, TimerToken; error, ExtensionError);
     define_method!(on_timeout; timer_id, TimerToken);
@@ -214,6 +647,7 @@ mod tests {
     use parking_lot::Mutex;
 
     use super::{Api, Client, ExtensionError, NetworkExtension, NodeToken};
+    use super::super::priority::{Priority, SendError};
 
     #[allow(dead_code)]
     struct TestApi;
@@ -223,10 +657,27 @@ mod tests {
             unimplemented!()
         }
 
+        fn send_with_priority(
+            &self,
+            _id: &usize,
+            _message: &Vec<u8>,
+            _priority: Priority,
+        ) -> Result<(), SendError> {
+            unimplemented!()
+        }
+
         fn connect(&self, _id: &usize) {
             unimplemented!()
         }
 
+        fn connect_via_rendezvous(&self, _id: &usize, _rendezvous: &usize) {
+            unimplemented!()
+        }
+
+        fn note_negotiated(&self, _id: &usize, _version: u64) {
+            unimplemented!()
+        }
+
         fn set_timer(&self, _timer_id: usize, _ms: u64) {
             unimplemented!()
         }
@@ -0,0 +1,271 @@
+// Copyright 2018 Kodebox, Inc.
+// This file is part of CodeChain.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::net::SocketAddr;
+
+use super::NodeToken;
+
+/// Timer used to keep NAT mappings open by periodically sending small UDP
+/// packets to the peer of every in-flight hole-punch attempt whose observed
+/// address is known. Other peers get no keepalive packets. Reuses the
+/// extension timer machinery so the interval is driven by `Api::set_timer`.
+pub const KEEPALIVE_TIMER: usize = 0xFF00;
+
+/// How often keepalive packets are emitted, in milliseconds. NAT mappings on
+/// most consumer routers expire after ~30s of idleness, so we refresh well
+/// inside that window.
+pub const KEEPALIVE_INTERVAL_MS: u64 = 15_000;
+
+/// How long we fire synchronized packets at a peer's observed address before
+/// giving up and falling back to relaying through the rendezvous node.
+pub const PUNCH_TIMEOUT_MS: u64 = 3_000;
+
+/// Timer used to re-fire the punch packet while an attempt is in
+/// `State::Punching` and to notice `PUNCH_TIMEOUT_MS` passing. Distinct from
+/// `KEEPALIVE_TIMER`: that one refreshes NAT mappings on an interval an order
+/// of magnitude longer than the whole punch window, so driving the punch
+/// retries from it meant the deadline was always stale by the time a tick
+/// observed it and no packet was ever re-sent.
+pub const PUNCH_TIMER: usize = 0xFF01;
+
+/// How often the punch timer ticks. Several multiples of this must fit inside
+/// `PUNCH_TIMEOUT_MS` for the retransmission to be meaningful.
+pub const PUNCH_INTERVAL_MS: u64 = 500;
+
+/// The externally-observed `(ip, port)` of an endpoint, as seen by a
+/// mutually-reachable rendezvous node. Both peers fire packets at the other's
+/// observed address simultaneously so the NAT mappings open in both
+/// directions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ObservedAddress {
+    pub node_id: NodeToken,
+    pub addr: SocketAddr,
+}
+
+impl ObservedAddress {
+    pub fn new(node_id: NodeToken, addr: SocketAddr) -> Self {
+        Self {
+            node_id,
+            addr,
+        }
+    }
+}
+
+/// Why a hole-punch attempt ended without a direct path being established.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HolePunchError {
+    /// The simultaneous-open packets never produced a reply within
+    /// `PUNCH_TIMEOUT_MS`; the caller relays through the rendezvous node.
+    TimedOut,
+    /// The rendezvous node could not observe one of the endpoints' addresses.
+    RendezvousUnreachable,
+}
+
+/// Stage of a single hole-punch attempt against one target.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum State {
+    /// Nothing requested yet.
+    Idle,
+    /// The rendezvous node has been asked to exchange observed addresses.
+    AwaitingRendezvous,
+    /// Both endpoints' addresses are known; firing packets until `deadline_ms`.
+    Punching {
+        deadline_ms: u64,
+    },
+    /// A reply arrived on the direct path; the peer is reachable.
+    Connected,
+    /// Punching timed out; traffic is relayed through the rendezvous node.
+    Relaying,
+}
+
+/// What the caller (the connection layer, which owns the UDP socket and the
+/// `IoChannel`) should do after feeding an input to the state machine. The
+/// machine itself is intentionally side-effect free so it can be unit tested.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Ask the rendezvous node to relay both endpoints' observed addresses.
+    RequestRelay,
+    /// Fire a UDP packet at the peer's observed address; called on both sides
+    /// simultaneously so the NAT mappings open in both directions.
+    SendPunch(SocketAddr),
+    /// Give up on the direct path and relay through the rendezvous node;
+    /// `on_hole_punch_failed` should be delivered to the extension.
+    Relay(HolePunchError),
+    /// The direct path is up; `on_hole_punch_succeeded` should be delivered.
+    Succeeded,
+    /// Nothing to do yet.
+    Wait,
+}
+
+/// Drives one node's side of a NAT hole-punch. Timestamps are supplied by the
+/// caller rather than read from the clock so the progression is deterministic
+/// and testable; the connection layer passes the current monotonic time.
+pub struct HolePunch {
+    target: NodeToken,
+    rendezvous: NodeToken,
+    extension_name: String,
+    state: State,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl HolePunch {
+    pub fn new(target: NodeToken, rendezvous: NodeToken, extension_name: String) -> Self {
+        Self {
+            target,
+            rendezvous,
+            extension_name,
+            state: State::Idle,
+            peer_addr: None,
+        }
+    }
+
+    pub fn target(&self) -> NodeToken {
+        self.target
+    }
+
+    pub fn rendezvous(&self) -> NodeToken {
+        self.rendezvous
+    }
+
+    /// Name of the extension that initiated this attempt, so the driver can
+    /// deliver `on_hole_punch_succeeded`/`on_hole_punch_failed` to it.
+    pub fn extension_name(&self) -> &str {
+        &self.extension_name
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The peer's externally-observed address, once the rendezvous node has
+    /// reported it (i.e. once punching has started). `None` before that, or
+    /// after falling back to relaying.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Begin the attempt by asking the rendezvous node to exchange addresses.
+    pub fn start(&mut self) -> Action {
+        self.state = State::AwaitingRendezvous;
+        Action::RequestRelay
+    }
+
+    /// The rendezvous node reported the peer's externally-observed address.
+    /// Move to the punching stage and fire the first packet.
+    pub fn on_rendezvous(&mut self, observed: ObservedAddress, now_ms: u64) -> Action {
+        if observed.node_id != self.target {
+            return Action::Wait
+        }
+        self.peer_addr = Some(observed.addr);
+        self.state = State::Punching {
+            deadline_ms: now_ms + PUNCH_TIMEOUT_MS,
+        };
+        Action::SendPunch(observed.addr)
+    }
+
+    /// A packet came back from the peer on the direct path.
+    pub fn on_peer_reply(&mut self) -> Action {
+        match self.state {
+            State::Punching {
+                ..
+            } => {
+                self.state = State::Connected;
+                Action::Succeeded
+            }
+            _ => Action::Wait,
+        }
+    }
+
+    /// Called on each `PUNCH_TIMER` tick. Re-fires the punch packet while inside
+    /// the window and falls back to relaying once `deadline_ms` passes.
+    pub fn poll(&mut self, now_ms: u64) -> Action {
+        match self.state {
+            State::Punching {
+                deadline_ms,
+            } => {
+                if now_ms >= deadline_ms {
+                    self.state = State::Relaying;
+                    self.peer_addr = None;
+                    Action::Relay(HolePunchError::TimedOut)
+                } else if let Some(addr) = self.peer_addr {
+                    Action::SendPunch(addr)
+                } else {
+                    Action::Wait
+                }
+            }
+            _ => Action::Wait,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr() -> SocketAddr {
+        "203.0.113.7:3485".parse().unwrap()
+    }
+
+    #[test]
+    fn start_requests_relay() {
+        let mut punch = HolePunch::new(7, 3, "e1".to_string());
+        assert_eq!(punch.start(), Action::RequestRelay);
+        assert_eq!(punch.state(), State::AwaitingRendezvous);
+    }
+
+    #[test]
+    fn rendezvous_starts_punching() {
+        let mut punch = HolePunch::new(7, 3, "e1".to_string());
+        punch.start();
+        let action = punch.on_rendezvous(ObservedAddress::new(7, addr()), 1_000);
+        assert_eq!(action, Action::SendPunch(addr()));
+        assert_eq!(
+            punch.state(),
+            State::Punching {
+                deadline_ms: 1_000 + PUNCH_TIMEOUT_MS,
+            }
+        );
+    }
+
+    #[test]
+    fn rendezvous_for_other_node_is_ignored() {
+        let mut punch = HolePunch::new(7, 3, "e1".to_string());
+        punch.start();
+        assert_eq!(punch.on_rendezvous(ObservedAddress::new(8, addr()), 1_000), Action::Wait);
+        assert_eq!(punch.state(), State::AwaitingRendezvous);
+    }
+
+    #[test]
+    fn reply_marks_connected() {
+        let mut punch = HolePunch::new(7, 3, "e1".to_string());
+        punch.start();
+        punch.on_rendezvous(ObservedAddress::new(7, addr()), 1_000);
+        assert_eq!(punch.on_peer_reply(), Action::Succeeded);
+        assert_eq!(punch.state(), State::Connected);
+    }
+
+    #[test]
+    fn timeout_falls_back_to_relaying() {
+        let mut punch = HolePunch::new(7, 3, "e1".to_string());
+        punch.start();
+        punch.on_rendezvous(ObservedAddress::new(7, addr()), 1_000);
+        // Still inside the window: keep firing.
+        assert_eq!(punch.poll(1_000 + PUNCH_TIMEOUT_MS - 1), Action::SendPunch(addr()));
+        // Past the deadline: relay.
+        assert_eq!(punch.poll(1_000 + PUNCH_TIMEOUT_MS), Action::Relay(HolePunchError::TimedOut));
+        assert_eq!(punch.state(), State::Relaying);
+    }
+}
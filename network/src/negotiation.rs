@@ -0,0 +1,69 @@
+// Copyright 2018 Kodebox, Inc.
+// This file is part of CodeChain.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::ops::Range;
+
+/// Version and feature bits agreed with a peer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Negotiated {
+    pub version: u64,
+    pub features: u64,
+}
+
+/// Computes the intersection of two `(versions, features)` offers. The agreed
+/// version is the highest value present in both half-open ranges; the agreed
+/// feature bits are those both sides advertise. Returns `None` when the version
+/// ranges do not overlap, which the connection layer surfaces as
+/// `on_negotiation_failed`.
+pub fn negotiate(
+    local_versions: &Range<u64>,
+    local_features: u64,
+    remote_versions: &Range<u64>,
+    remote_features: u64,
+) -> Option<Negotiated> {
+    let start = local_versions.start.max(remote_versions.start);
+    let end = local_versions.end.min(remote_versions.end);
+    if start >= end {
+        return None
+    }
+    Some(Negotiated {
+        version: end - 1,
+        features: local_features & remote_features,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_highest_common_version() {
+        let agreed = negotiate(&(0..4), 0b1111, &(2..6), 0b1010).unwrap();
+        assert_eq!(agreed.version, 3);
+        assert_eq!(agreed.features, 0b1010);
+    }
+
+    #[test]
+    fn disjoint_ranges_do_not_negotiate() {
+        assert_eq!(negotiate(&(0..2), 0xff, &(3..5), 0xff), None);
+    }
+
+    #[test]
+    fn touching_ranges_do_not_overlap() {
+        // `0..2` ends before `2`, so there is no version in common.
+        assert_eq!(negotiate(&(0..2), 0xff, &(2..4), 0xff), None);
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright 2018 Kodebox, Inc.
+// This file is part of CodeChain.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::VecDeque;
+
+/// Default bound on the number of queued messages per priority class, per
+/// destination peer of an extension. Once a class is full, `SendQueue::enqueue` returns
+/// `SendError::WouldBlock` so the caller backs off instead of flooding the
+/// shared `IoChannel`.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 4096;
+
+/// Timer that drives `Client::drive_sends`, the round-robin drainer that
+/// services every registered extension's send queue. Reuses the extension
+/// timer machinery, same as `hole_punch`'s `KEEPALIVE_TIMER`/`PUNCH_TIMER`.
+pub const SEND_TIMER: usize = 0xFF02;
+
+/// How often the send-queue drainer ticks.
+pub const SEND_INTERVAL_MS: u64 = 50;
+
+/// Priority class of an outbound extension message. The connection layer drains
+/// queues round-robin across extensions, and each turn emits up to
+/// `weight()` messages of each class, control first, so that control traffic
+/// is not starved by bulk transfers.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Priority {
+    /// Bulk data that may be delayed or, under pressure, dropped.
+    Bulk,
+    /// Latency-sensitive control traffic that is drained ahead of bulk.
+    Control,
+}
+
+impl Priority {
+    /// Relative weight given to this class when the drainer shares turns across
+    /// extensions: how many messages of this class are emitted per round.
+    pub fn weight(self) -> usize {
+        match self {
+            Priority::Bulk => 1,
+            Priority::Control => 4,
+        }
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Bulk
+    }
+}
+
+/// Outcome of enqueueing a message onto a bounded per-extension send queue.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SendError {
+    /// The queue for this priority class is full; the caller should back off and
+    /// retry after `on_send_congested`. Transient.
+    WouldBlock,
+    /// The message could not be enqueued because the extension is gone.
+    /// Permanent.
+    Dropped,
+}
+
+/// A bounded, priority-classed send buffer for one destination peer of one
+/// extension. The connection layer keeps one of these per peer per registered
+/// extension and drains them round-robin; within a single queue `drain_round` emits `weight()` control
+/// messages ahead of `weight()` bulk messages so control traffic wins.
+pub struct SendQueue {
+    control: VecDeque<Vec<u8>>,
+    bulk: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl SendQueue {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            control: VecDeque::new(),
+            bulk: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn class(&mut self, priority: Priority) -> &mut VecDeque<Vec<u8>> {
+        match priority {
+            Priority::Control => &mut self.control,
+            Priority::Bulk => &mut self.bulk,
+        }
+    }
+
+    /// Appends `message` to the requested class, or returns `WouldBlock` when
+    /// that class is already at capacity.
+    pub fn enqueue(&mut self, priority: Priority, message: Vec<u8>) -> Result<(), SendError> {
+        let capacity = self.capacity;
+        let class = self.class(priority);
+        if class.len() >= capacity {
+            return Err(SendError::WouldBlock)
+        }
+        class.push_back(message);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.control.is_empty() && self.bulk.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.control.len() + self.bulk.len()
+    }
+
+    /// Pops one weighted round: up to `Priority::Control.weight()` control
+    /// messages followed by up to `Priority::Bulk.weight()` bulk messages, in
+    /// send order. Returns an empty vector when the queue is drained.
+    pub fn drain_round(&mut self) -> Vec<Vec<u8>> {
+        let mut round = Vec::new();
+        for _ in 0..Priority::Control.weight() {
+            match self.control.pop_front() {
+                Some(message) => round.push(message),
+                None => break,
+            }
+        }
+        for _ in 0..Priority::Bulk.weight() {
+            match self.bulk.pop_front() {
+                Some(message) => round.push(message),
+                None => break,
+            }
+        }
+        round
+    }
+}
+
+impl Default for SendQueue {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_QUEUE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enqueue_blocks_when_class_full() {
+        let mut queue = SendQueue::with_capacity(1);
+        assert_eq!(queue.enqueue(Priority::Bulk, vec![1]), Ok(()));
+        assert_eq!(queue.enqueue(Priority::Bulk, vec![2]), Err(SendError::WouldBlock));
+        // The other class has its own budget.
+        assert_eq!(queue.enqueue(Priority::Control, vec![3]), Ok(()));
+    }
+
+    #[test]
+    fn round_drains_control_ahead_of_bulk() {
+        let mut queue = SendQueue::with_capacity(16);
+        for i in 0..6 {
+            queue.enqueue(Priority::Bulk, vec![i]).unwrap();
+        }
+        for i in 10..16 {
+            queue.enqueue(Priority::Control, vec![i]).unwrap();
+        }
+        // First round: 4 control (weight 4) then 1 bulk (weight 1).
+        let round = queue.drain_round();
+        assert_eq!(round, vec![vec![10], vec![11], vec![12], vec![13], vec![0]]);
+    }
+
+    #[test]
+    fn drains_to_empty_over_rounds() {
+        let mut queue = SendQueue::with_capacity(16);
+        queue.enqueue(Priority::Control, vec![1]).unwrap();
+        queue.enqueue(Priority::Bulk, vec![2]).unwrap();
+        let mut drained = Vec::new();
+        while !queue.is_empty() {
+            drained.extend(queue.drain_round());
+        }
+        assert_eq!(drained, vec![vec![1], vec![2]]);
+        assert!(queue.drain_round().is_empty());
+    }
+}